@@ -1,16 +1,43 @@
 #[macro_use]
 extern crate serde_derive;
 
+extern crate chacha20;
+extern crate filetime;
 extern crate futures;
+extern crate notify;
+extern crate rand;
 extern crate serde;
 extern crate serde_json;
 extern crate textnonce;
+extern crate uuid;
 
-use std::error::Error as StdError;
+#[cfg(all(target_os = "linux", feature = "io_uring"))]
+extern crate io_uring;
+#[cfg(all(target_os = "linux", feature = "io_uring"))]
+extern crate libc;
+
+use std::io::{Read, Write};
 use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
 
+use chacha20::ChaCha20;
+use chacha20::stream_cipher::{NewStreamCipher, SyncStreamCipher};
+use rand::RngCore;
 use serde::{Serialize, Deserialize};
 
+/// Length in bytes of the random nonce written as a header on each encrypted item file.
+const NONCE_LEN: usize = 12;
+
+/// Length in bytes of an encryption key for `Queue::new_encrypted`.
+const KEY_LEN: usize = 32;
+
+/// The first line of a `dump` archive, identifying the item type and a unique id for the dump
+/// so a `restore` can refuse to load a dump into a mismatched queue.
+#[derive(Serialize, Deserialize)]
+struct DumpManifest {
+    uuid: String,
+    item_type: String,
+}
+
 /// A durable queue backed by the filesystem.
 ///
 /// This queue stores items of type T as files in a spool directory. Files are serialized with
@@ -18,19 +45,42 @@ use serde::{Serialize, Deserialize};
 ///
 /// The queue's directory should only contain items of the same type. Any files in the spool
 /// that fail to deserialize will be discarded.
+///
+/// Use `new_encrypted` instead of `new` to keep item contents encrypted at rest.
 pub struct Queue<T> {
     path: String,
     seq: u32,
+    key: Option<[u8; KEY_LEN]>,
     _placeholder: std::marker::PhantomData<T>,
 }
 
 impl<T: Serialize + Deserialize> Queue<T> {
     /// Create a new `Queue<T>` using the given directory path for storage.
+    ///
+    /// The spool directory is scanned for any existing entries so that `seq` resumes one past
+    /// the highest sequence number already present. This avoids a restarted process resetting
+    /// `seq` to zero and pushing new items that sort ahead of older ones still in the queue.
     pub fn new(path: &str) -> Result<Queue<T>, std::io::Error> {
+        Queue::<T>::new_with_key(path, None)
+    }
+
+    /// Create a new `Queue<T>` that encrypts item contents at rest.
+    ///
+    /// Each pushed item is serialized in full and encrypted in memory with a ChaCha20 cipher
+    /// keyed with `key`, using a fresh random nonce per item, before it is written to disk, so
+    /// that the spool directory never holds plaintext. The serialized output is not streamed
+    /// through the cipher. The same `key` must be supplied to read the queue back.
+    pub fn new_encrypted(path: &str, key: [u8; KEY_LEN]) -> Result<Queue<T>, std::io::Error> {
+        Queue::<T>::new_with_key(path, Some(key))
+    }
+
+    fn new_with_key(path: &str, key: Option<[u8; KEY_LEN]>) -> Result<Queue<T>, std::io::Error> {
         std::fs::DirBuilder::new().recursive(true).mode(0o700).create(path)?;
+        let seq = max_seq(path)?.map_or(0u32, |s| (s + 1) as u32);
         Ok(Queue::<T> {
             path: path.to_string(),
-            seq: 0,
+            seq: seq,
+            key: key,
             _placeholder: std::marker::PhantomData,
         })
     }
@@ -48,7 +98,17 @@ impl<T: Serialize + Deserialize> Queue<T> {
                 .mode(0o600)
                 .create_new(true)
                 .open(&incomplete_path)?;
-            serde_json::to_writer(&mut item_file, &item).map_err(to_ioerror)?;
+            match self.key {
+                Some(key) => {
+                    let mut nonce = [0u8; NONCE_LEN];
+                    rand::thread_rng().fill_bytes(&mut nonce);
+                    let mut buf = serde_json::to_vec(&item).map_err(to_ioerror)?;
+                    ChaCha20::new_var(&key, &nonce).map_err(to_ioerror)?.apply_keystream(&mut buf);
+                    item_file.write_all(&nonce)?;
+                    item_file.write_all(&buf)?;
+                }
+                None => serde_json::to_writer(&mut item_file, &item).map_err(to_ioerror)?,
+            }
         }
         std::fs::rename(incomplete_path, complete_path)?;
         self.seq += 1;
@@ -57,36 +117,54 @@ impl<T: Serialize + Deserialize> Queue<T> {
 
     /// Pop an item off the queue.
     ///
-    /// This method returns the first matching directory entry. Queue ordering cannot be guaranteed
-    /// to be consistent across all operating systems and filesystems, as the serialized file will
-    /// be chosen based on the filesystem's directory entry ordering.
+    /// Candidate entries (those without an extension) are ordered by the sequence number
+    /// encoded in their filename prefix, so this method always returns the oldest item still
+    /// in the queue, giving deterministic first-in-first-out delivery across all filesystems.
     ///
     /// Popped items are not removed from the filesystem immediately; instead, they are marked for
     /// deletion. Use flush() to cause the items to be permanently removed from the underlying
     /// filesystem.
     pub fn pop(&self) -> Result<Option<T>, std::io::Error> {
         let dirh = std::fs::read_dir(&self.path)?;
+        let mut oldest: Option<(u64, std::path::PathBuf)> = None;
         for maybe_dirent in dirh {
-            let item_path = match maybe_dirent {
-                Ok(dirent) => {
-                    let p = dirent.path();
-                    if let Some(_) = p.extension() {
-                        continue;
-                    }
-                    p
-                }
+            let p = match maybe_dirent {
+                Ok(dirent) => dirent.path(),
                 Err(e) => return Err(e),
             };
-            let stage_path = item_path.with_extension("pop");
-            {
-                let item_file = std::fs::OpenOptions::new().read(true)
-                    .open(&item_path)?;
-                let item = serde_json::from_reader(item_file).map_err(to_ioerror)?;
-                std::fs::rename(item_path, stage_path)?;
-                return Ok(Some(item));
+            if p.extension().is_some() {
+                continue;
+            }
+            let seq = match seq_prefix(&p) {
+                Some(seq) => seq,
+                None => continue,
+            };
+            if oldest.as_ref().map_or(true, |&(oldest_seq, _)| seq < oldest_seq) {
+                oldest = Some((seq, p));
             }
         }
-        Ok(None)
+        let item_path = match oldest {
+            Some((_, p)) => p,
+            None => return Ok(None),
+        };
+        let stage_path = item_path.with_extension("pop");
+        let mut item_file = std::fs::OpenOptions::new().read(true).open(&item_path)?;
+        let item = match self.key {
+            Some(key) => {
+                let mut nonce = [0u8; NONCE_LEN];
+                item_file.read_exact(&mut nonce)?;
+                let mut buf = Vec::new();
+                item_file.read_to_end(&mut buf)?;
+                ChaCha20::new_var(&key, &nonce).map_err(to_ioerror)?.apply_keystream(&mut buf);
+                serde_json::from_slice(&buf).map_err(to_ioerror)?
+            }
+            None => serde_json::from_reader(item_file).map_err(to_ioerror)?,
+        };
+        std::fs::rename(item_path, &stage_path)?;
+        // rename() does not update mtime, so stamp it explicitly: reclaim() relies on the
+        // `.pop` file's mtime recording when the item was popped, not when it was pushed.
+        filetime::set_file_mtime(&stage_path, filetime::FileTime::now()).map_err(to_ioerror)?;
+        Ok(Some(item))
     }
 
     /// Flush removes all pending item files marked for deletion.
@@ -138,11 +216,432 @@ impl<T: Serialize + Deserialize> Queue<T> {
         }
         Ok(())
     }
+
+    /// Reclaim popped-but-unflushed items whose visibility timeout has expired.
+    ///
+    /// Any item staged with `pop` more than `timeout` ago and not yet `flush`ed or `recover`ed is
+    /// renamed back to its visible name so it can be redelivered. Unlike `recover`, this leaves
+    /// alone items that are still within their visibility timeout, so it's safe to call
+    /// periodically without re-delivering work that's still being processed.
+    pub fn reclaim(&self, timeout: std::time::Duration) -> Result<(), std::io::Error> {
+        let now = filetime::FileTime::now();
+        let dirh = std::fs::read_dir(&self.path)?;
+        for maybe_dirent in dirh {
+            match maybe_dirent {
+                Ok(dirent) => {
+                    let p = dirent.path();
+                    match p.extension() {
+                        Some(e) => {
+                            if e != "pop" {
+                                continue;
+                            }
+                        }
+                        None => continue,
+                    }
+                    let popped_at = filetime::FileTime::from_last_modification_time(&dirent.metadata()?);
+                    if nanos_since(popped_at, now) < timeout {
+                        continue;
+                    }
+                    let unmarked =
+                        p.parent().unwrap().join(std::path::Path::new(p.file_stem().unwrap()));
+                    std::fs::rename(p, unmarked)?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Time remaining until the soonest popped-but-unflushed item becomes reclaimable under
+    /// `timeout`, or `None` if nothing is currently staged.
+    ///
+    /// `QueueStream::poll` uses this to schedule a timer-based wakeup for an idle consumer: an
+    /// item's visibility timeout expires on a clock, not a filesystem event, so the watcher alone
+    /// would never notice it become due for redelivery.
+    fn next_reclaim_in(&self,
+                        timeout: std::time::Duration)
+                        -> Result<Option<std::time::Duration>, std::io::Error> {
+        let now = filetime::FileTime::now();
+        let mut soonest: Option<std::time::Duration> = None;
+        let dirh = std::fs::read_dir(&self.path)?;
+        for maybe_dirent in dirh {
+            let dirent = maybe_dirent?;
+            let p = dirent.path();
+            match p.extension() {
+                Some(e) => {
+                    if e != "pop" {
+                        continue;
+                    }
+                }
+                None => continue,
+            }
+            let popped_at = filetime::FileTime::from_last_modification_time(&dirent.metadata()?);
+            let elapsed = nanos_since(popped_at, now);
+            let remaining = timeout.checked_sub(elapsed).unwrap_or(std::time::Duration::from_secs(0));
+            if soonest.map_or(true, |s| remaining < s) {
+                soonest = Some(remaining);
+            }
+        }
+        Ok(soonest)
+    }
+
+    /// Snapshot every visible item into a single portable archive.
+    ///
+    /// The archive is a newline-delimited stream: a manifest line identifying the item type and
+    /// a generated UUID for the dump, followed by one line per item's raw JSON payload, in
+    /// sequence order. Items still staged by `pop` or `push` are left untouched, so a live queue
+    /// can be dumped concurrently.
+    pub fn dump<W: std::io::Write>(&self, mut writer: W) -> Result<(), std::io::Error> {
+        let manifest = DumpManifest {
+            uuid: uuid::Uuid::new_v4().to_string(),
+            item_type: std::any::type_name::<T>().to_string(),
+        };
+        serde_json::to_writer(&mut writer, &manifest).map_err(to_ioerror)?;
+        writer.write_all(b"\n")?;
+        for item_path in visible_entries_sorted(&self.path)? {
+            let mut item_file = match std::fs::OpenOptions::new().read(true).open(&item_path) {
+                Ok(f) => f,
+                // A concurrent pop() can rename this entry to `.pop` between listing and
+                // opening it; skip it rather than aborting the whole dump.
+                Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            let raw = match self.key {
+                Some(key) => {
+                    let mut nonce = [0u8; NONCE_LEN];
+                    item_file.read_exact(&mut nonce)?;
+                    let mut buf = Vec::new();
+                    item_file.read_to_end(&mut buf)?;
+                    ChaCha20::new_var(&key, &nonce).map_err(to_ioerror)?.apply_keystream(&mut buf);
+                    buf
+                }
+                None => {
+                    let mut buf = Vec::new();
+                    item_file.read_to_end(&mut buf)?;
+                    buf
+                }
+            };
+            writer.write_all(&raw)?;
+            writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    /// Restore items from a `dump` archive into this (normally freshly created) queue.
+    ///
+    /// Items are pushed back in the order they appear in the archive, re-minting filenames with
+    /// correct sequence prefixes. Returns an error if the archive's manifest identifies a
+    /// different item type than this queue's `T`.
+    pub fn restore<R: std::io::Read>(&mut self, reader: R) -> Result<(), std::io::Error> {
+        let mut lines = std::io::BufRead::lines(std::io::BufReader::new(reader));
+        let manifest_line = match lines.next() {
+            Some(line) => line?,
+            None => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "dump archive is missing its manifest")),
+        };
+        let manifest: DumpManifest = serde_json::from_str(&manifest_line).map_err(to_ioerror)?;
+        let item_type = std::any::type_name::<T>();
+        if manifest.item_type != item_type {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+                                            format!("dump item type \"{}\" does not match queue item type \"{}\"",
+                                                    manifest.item_type,
+                                                    item_type)));
+        }
+        for line in lines {
+            let item: T = serde_json::from_str(&line?).map_err(to_ioerror)?;
+            self.push(item)?;
+        }
+        Ok(())
+    }
+}
+
+/// Synchronous fallback for `push_batch`/`pop_batch`, used whenever the `io_uring` feature is
+/// disabled or the target isn't Linux.
+#[cfg(not(all(target_os = "linux", feature = "io_uring")))]
+impl<T: Serialize + Deserialize> Queue<T> {
+    /// Push a batch of items into the Queue.
+    pub fn push_batch(&mut self, items: Vec<T>) -> Result<(), std::io::Error> {
+        for item in items {
+            self.push(item)?;
+        }
+        Ok(())
+    }
+
+    /// Pop up to `max` items off the queue.
+    pub fn pop_batch(&self, max: usize) -> Result<Vec<T>, std::io::Error> {
+        let mut items = Vec::with_capacity(max);
+        while items.len() < max {
+            match self.pop()? {
+                Some(item) => items.push(item),
+                None => break,
+            }
+        }
+        Ok(items)
+    }
+}
+
+/// io_uring-backed fast path for `push_batch`/`pop_batch`, submitting the writes/reads and
+/// renames for a whole batch to a shared ring instead of issuing them syscall-by-syscall.
+/// Keeps the same write-to-`.inc`-then-rename durability contract as the synchronous path.
+#[cfg(all(target_os = "linux", feature = "io_uring"))]
+impl<T: Serialize + Deserialize> Queue<T> {
+    /// Push a batch of items into the Queue, submitting all writes and renames in one ring.
+    pub fn push_batch(&mut self, items: Vec<T>) -> Result<(), std::io::Error> {
+        io_uring_batch::push_batch(self, items)
+    }
+
+    /// Pop up to `max` items off the queue, prefetching reads and renames in one ring.
+    pub fn pop_batch(&self, max: usize) -> Result<Vec<T>, std::io::Error> {
+        io_uring_batch::pop_batch(self, max)
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "io_uring"))]
+mod io_uring_batch {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::io::AsRawFd;
+
+    use io_uring::{opcode, types, IoUring};
+    use rand::RngCore;
+    use serde::{Deserialize, Serialize};
+
+    use super::{rand_string, to_ioerror, visible_entries_sorted, ChaCha20, Queue,
+                NewStreamCipher, SyncStreamCipher, NONCE_LEN};
+
+    fn path_cstring(path: &std::path::Path) -> Result<CString, std::io::Error> {
+        CString::new(path.as_os_str().as_bytes()).map_err(to_ioerror)
+    }
+
+    /// Wait for `count` completions on `ring` and turn the first error into an `io::Error`.
+    ///
+    /// Used for operations (like renames) where any non-negative result means success.
+    fn drain_completions(ring: &mut IoUring, count: usize) -> Result<(), std::io::Error> {
+        ring.submit_and_wait(count).map_err(to_ioerror)?;
+        for cqe in ring.completion() {
+            if cqe.result() < 0 {
+                return Err(std::io::Error::from_raw_os_error(-cqe.result()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait for `count` read/write completions on `ring`, indexed by `user_data` into
+    /// `expected_lens`, and error on a short transfer as well as a negative result. A short
+    /// write would otherwise silently truncate the `.inc` file before it gets renamed into
+    /// place, breaking the atomic-rename durability contract.
+    fn drain_sized_completions(ring: &mut IoUring,
+                                count: usize,
+                                expected_lens: &[usize])
+                                -> Result<(), std::io::Error> {
+        ring.submit_and_wait(count).map_err(to_ioerror)?;
+        for cqe in ring.completion() {
+            let result = cqe.result();
+            if result < 0 {
+                return Err(std::io::Error::from_raw_os_error(-result));
+            }
+            let expected = expected_lens[cqe.user_data() as usize];
+            if result as usize != expected {
+                return Err(std::io::Error::new(std::io::ErrorKind::Other,
+                                                format!("short io_uring transfer: {} of {} bytes",
+                                                        result,
+                                                        expected)));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn push_batch<T: Serialize + Deserialize>(queue: &mut Queue<T>,
+                                                    items: Vec<T>)
+                                                    -> Result<(), std::io::Error> {
+        if items.is_empty() {
+            return Ok(());
+        }
+
+        struct Staged {
+            file: std::fs::File,
+            buf: Vec<u8>,
+            incomplete_path: CString,
+            complete_path: CString,
+        }
+
+        let mut staged = Vec::with_capacity(items.len());
+        for item in items {
+            let mut item_path = std::path::PathBuf::from(&queue.path);
+            let item_name = format!("{:016x}-{}", queue.seq, rand_string());
+            item_path.push(item_name);
+            let complete_path = item_path.clone();
+            let incomplete_path = item_path.with_extension("inc");
+            queue.seq += 1;
+
+            let file = std::fs::OpenOptions::new().write(true)
+                .mode(0o600)
+                .create_new(true)
+                .open(&incomplete_path)?;
+
+            let mut buf = ::serde_json::to_vec(&item).map_err(to_ioerror)?;
+            if let Some(key) = queue.key {
+                let mut nonce = [0u8; NONCE_LEN];
+                ::rand::thread_rng().fill_bytes(&mut nonce);
+                ChaCha20::new_var(&key, &nonce).map_err(to_ioerror)?.apply_keystream(&mut buf);
+                let mut framed = Vec::with_capacity(NONCE_LEN + buf.len());
+                framed.extend_from_slice(&nonce);
+                framed.extend_from_slice(&buf);
+                buf = framed;
+            }
+
+            staged.push(Staged {
+                file: file,
+                buf: buf,
+                incomplete_path: path_cstring(&incomplete_path)?,
+                complete_path: path_cstring(&complete_path)?,
+            });
+        }
+
+        let mut ring = IoUring::new(staged.len() as u32).map_err(to_ioerror)?;
+
+        let write_lens: Vec<usize> = staged.iter().map(|s| s.buf.len()).collect();
+        for (i, s) in staged.iter().enumerate() {
+            let write_e = opcode::Write::new(types::Fd(s.file.as_raw_fd()), s.buf.as_ptr(), s.buf.len() as u32)
+                .build()
+                .user_data(i as u64);
+            unsafe {
+                ring.submission().push(&write_e).map_err(to_ioerror)?;
+            }
+        }
+        drain_sized_completions(&mut ring, staged.len(), &write_lens)?;
+
+        for s in staged.iter() {
+            let rename_e = opcode::RenameAt::new(types::Fd(libc::AT_FDCWD),
+                                                  s.incomplete_path.as_ptr(),
+                                                  types::Fd(libc::AT_FDCWD),
+                                                  s.complete_path.as_ptr())
+                .build();
+            unsafe {
+                ring.submission().push(&rename_e).map_err(to_ioerror)?;
+            }
+        }
+        drain_completions(&mut ring, staged.len())?;
+
+        Ok(())
+    }
+
+    pub fn pop_batch<T: Serialize + Deserialize>(queue: &Queue<T>,
+                                                  max: usize)
+                                                  -> Result<Vec<T>, std::io::Error> {
+        let paths: Vec<_> = visible_entries_sorted(&queue.path)?.into_iter().take(max).collect();
+        if paths.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let files: Vec<std::fs::File> = paths.iter()
+            .map(|p| std::fs::OpenOptions::new().read(true).open(p))
+            .collect::<Result<_, _>>()?;
+        let mut bufs: Vec<Vec<u8>> = files.iter()
+            .map(|f| f.metadata().map(|m| vec![0u8; m.len() as usize]))
+            .collect::<Result<_, _>>()?;
+
+        let mut ring = IoUring::new(paths.len() as u32).map_err(to_ioerror)?;
+        let read_lens: Vec<usize> = bufs.iter().map(|b| b.len()).collect();
+        for (i, file) in files.iter().enumerate() {
+            let read_e = opcode::Read::new(types::Fd(file.as_raw_fd()), bufs[i].as_mut_ptr(), bufs[i].len() as u32)
+                .build()
+                .user_data(i as u64);
+            unsafe {
+                ring.submission().push(&read_e).map_err(to_ioerror)?;
+            }
+        }
+        drain_sized_completions(&mut ring, paths.len(), &read_lens)?;
+
+        // Decode before renaming, same as the sync `pop`: a malformed or undecryptable entry is
+        // left visible rather than staged invisible and stranded, so skip it instead of renaming.
+        let key = queue.key;
+        let mut items = Vec::with_capacity(bufs.len());
+        let mut decoded_idx = Vec::with_capacity(bufs.len());
+        for (i, mut buf) in bufs.into_iter().enumerate() {
+            let decoded: Option<T> = if let Some(key) = key {
+                if buf.len() < NONCE_LEN {
+                    None
+                } else {
+                    let nonce: [u8; NONCE_LEN] = {
+                        let mut n = [0u8; NONCE_LEN];
+                        n.copy_from_slice(&buf[..NONCE_LEN]);
+                        n
+                    };
+                    let mut payload = buf.split_off(NONCE_LEN);
+                    match ChaCha20::new_var(&key, &nonce) {
+                        Ok(mut cipher) => {
+                            cipher.apply_keystream(&mut payload);
+                            ::serde_json::from_slice(&payload).ok()
+                        }
+                        Err(_) => None,
+                    }
+                }
+            } else {
+                ::serde_json::from_slice(&buf).ok()
+            };
+            if let Some(item) = decoded {
+                items.push(item);
+                decoded_idx.push(i);
+            }
+        }
+        if decoded_idx.is_empty() {
+            return Ok(items);
+        }
+
+        let stage_paths: Vec<std::path::PathBuf> =
+            decoded_idx.iter().map(|&i| paths[i].with_extension("pop")).collect();
+        let orig_cstrings: Vec<CString> =
+            decoded_idx.iter().map(|&i| path_cstring(&paths[i])).collect::<Result<_, _>>()?;
+        let stage_cstrings: Vec<CString> =
+            stage_paths.iter().map(|p| path_cstring(p)).collect::<Result<_, _>>()?;
+        for i in 0..decoded_idx.len() {
+            let rename_e = opcode::RenameAt::new(types::Fd(libc::AT_FDCWD),
+                                                  orig_cstrings[i].as_ptr(),
+                                                  types::Fd(libc::AT_FDCWD),
+                                                  stage_cstrings[i].as_ptr())
+                .build();
+            unsafe {
+                ring.submission().push(&rename_e).map_err(to_ioerror)?;
+            }
+        }
+        drain_completions(&mut ring, decoded_idx.len())?;
+        for stage_path in &stage_paths {
+            ::filetime::set_file_mtime(stage_path, ::filetime::FileTime::now()).map_err(to_ioerror)?;
+        }
+
+        Ok(items)
+    }
+}
+
+/// Debounce passed to `notify::watcher`. On platforms with a native watcher (inotify on Linux,
+/// FSEvents on macOS, ReadDirectoryChangesW on Windows) events arrive immediately and this value
+/// only coalesces bursts, so it is kept tiny to wake a blocked consumer right away. Platforms
+/// without one of those fall back to `notify`'s polling watcher, for which this same value *is*
+/// the directory-scan interval, so a coarser value is used there instead of busy-polling.
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
+const WATCH_DEBOUNCE_MS: u64 = 10;
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+const WATCH_DEBOUNCE_MS: u64 = 1000;
+
+/// Wakes a parked futures task from the watcher thread once a new item appears.
+struct Waker {
+    task: std::sync::Mutex<Option<futures::task::Task>>,
+}
+
+/// Holds the live filesystem watch registered by a `QueueStream` once it has seen an empty
+/// queue. The watcher must stay alive for the duration of the watch, so it is kept here rather
+/// than dropped at the end of `ensure_watch`.
+struct QueueWatch {
+    _watcher: notify::RecommendedWatcher,
+    waker: std::sync::Arc<Waker>,
 }
 
 /// Process a Queue<T> as a stream of future values.
 pub struct QueueStream<T> {
     queue: Queue<T>,
+    watch: Option<QueueWatch>,
+    visibility_timeout: Option<std::time::Duration>,
 }
 
 impl<T: Serialize + Deserialize> QueueStream<T> {
@@ -159,7 +658,63 @@ impl<T: Serialize + Deserialize> QueueStream<T> {
 impl<T: Serialize + Deserialize> QueueStream<T> {
     /// Create a new QueueStream<T> with the given spool path.
     pub fn new(q: Queue<T>) -> QueueStream<T> {
-        QueueStream::<T> { queue: q }
+        QueueStream::<T> {
+            queue: q,
+            watch: None,
+            visibility_timeout: None,
+        }
+    }
+
+    /// Create a new QueueStream<T> that automatically reclaims popped-but-unflushed items once
+    /// `timeout` has elapsed, redelivering them to the stream as if they were never popped.
+    ///
+    /// This gives at-least-once delivery that self-heals after a consumer crashes between
+    /// `pop` and `flush`/`recover`, without requiring a blanket `recover()` that would also
+    /// re-deliver items still being processed.
+    pub fn new_with_visibility_timeout(q: Queue<T>, timeout: std::time::Duration) -> QueueStream<T> {
+        QueueStream::<T> {
+            queue: q,
+            watch: None,
+            visibility_timeout: Some(timeout),
+        }
+    }
+
+    /// Register a filesystem watch on the spool directory, if one isn't already running, and
+    /// spawn a thread that wakes the parked task whenever a new complete item appears.
+    ///
+    /// This uses `notify`'s recommended watcher for the target platform: native notifications
+    /// (inotify on Linux, FSEvents on macOS, ReadDirectoryChangesW on Windows), or `notify`'s
+    /// polling watcher on platforms without one. The choice is made per platform, not per
+    /// filesystem: on a filesystem where the native mechanism reports no events, no polling
+    /// fallback is used.
+    fn ensure_watch(&mut self) -> Result<(), std::io::Error> {
+        if self.watch.is_some() {
+            return Ok(());
+        }
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut watcher = notify::watcher(tx, std::time::Duration::from_millis(WATCH_DEBOUNCE_MS))
+            .map_err(to_ioerror)?;
+        notify::Watcher::watch(&mut watcher, &self.queue.path, notify::RecursiveMode::NonRecursive)
+            .map_err(to_ioerror)?;
+
+        let waker = std::sync::Arc::new(Waker { task: std::sync::Mutex::new(None) });
+        let thread_waker = waker.clone();
+        std::thread::spawn(move || {
+            for event in rx {
+                if !is_new_item_event(&event) {
+                    continue;
+                }
+                if let Some(task) = thread_waker.task.lock().unwrap().take() {
+                    task.notify();
+                }
+            }
+        });
+
+        self.watch = Some(QueueWatch {
+            _watcher: watcher,
+            waker: waker,
+        });
+        Ok(())
     }
 }
 
@@ -169,18 +724,61 @@ impl<T: Serialize + Deserialize> futures::stream::Stream for QueueStream<T> {
 
     /// Attempt to pop the next item off the stream.
     ///
-    /// This method polls the underlying filesystem watcher for changes since the last poll.
+    /// If a visibility timeout was configured, this first reclaims any popped-but-unflushed
+    /// items that have been staged longer than the timeout, so a crashed consumer doesn't starve
+    /// the stream. When the queue is empty, this registers (on first use) a filesystem watch on
+    /// the spool directory and parks the current task until the watcher observes a new item,
+    /// rather than busy-polling. If a visibility timeout is configured and an item is still
+    /// staged, a timer is also armed for when it becomes reclaimable, since its expiry is a clock
+    /// event the watcher would never otherwise notice.
     fn poll(&mut self) -> futures::Poll<Option<Self::Item>, Self::Error> {
+        if let Some(timeout) = self.visibility_timeout {
+            self.queue.reclaim(timeout)?;
+        }
         match self.queue.pop() {
             Ok(Some(t)) => Ok(futures::Async::Ready(Some(t))),
-            Ok(None) => Ok(futures::Async::NotReady),
+            Ok(None) => {
+                self.ensure_watch()?;
+                let waker = self.watch.as_ref().unwrap().waker.clone();
+                *waker.task.lock().unwrap() = Some(futures::task::current());
+                // A push can land between the pop() above and the task being stored; the
+                // watcher would notify against an empty slot and that wakeup would be lost.
+                // Re-check now that the task is registered so such a push is never missed.
+                match self.queue.pop()? {
+                    Some(t) => Ok(futures::Async::Ready(Some(t))),
+                    None => {
+                        if let Some(timeout) = self.visibility_timeout {
+                            if let Some(remaining) = self.queue.next_reclaim_in(timeout)? {
+                                let timer_waker = waker.clone();
+                                std::thread::spawn(move || {
+                                    std::thread::sleep(remaining);
+                                    if let Some(task) = timer_waker.task.lock().unwrap().take() {
+                                        task.notify();
+                                    }
+                                });
+                            }
+                        }
+                        Ok(futures::Async::NotReady)
+                    }
+                }
+            }
             Err(e) => Err(e),
         }
     }
 }
 
-fn to_ioerror<E: StdError>(e: E) -> std::io::Error {
-    std::io::Error::new(std::io::ErrorKind::Other, e.description())
+/// True if a notify event corresponds to a new complete (non-`.inc`, non-`.pop`) spool item
+/// becoming visible.
+fn is_new_item_event(event: &notify::DebouncedEvent) -> bool {
+    match *event {
+        notify::DebouncedEvent::Create(ref p) |
+        notify::DebouncedEvent::Rename(_, ref p) => p.extension().is_none(),
+        _ => false,
+    }
+}
+
+fn to_ioerror<E: std::fmt::Display>(e: E) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, e.to_string())
 }
 
 mod cleanup {
@@ -212,6 +810,54 @@ fn rand_string() -> String {
     textnonce::TextNonce::sized_urlsafe(32).unwrap().into_string()
 }
 
+/// Elapsed time between two `FileTime`s at full (sub-second) precision. `reclaim` uses this
+/// instead of comparing whole seconds, which would round a sub-second `timeout` unpredictably.
+fn nanos_since(earlier: filetime::FileTime, later: filetime::FileTime) -> std::time::Duration {
+    let earlier_nanos = earlier.seconds() as i128 * 1_000_000_000 + earlier.nanoseconds() as i128;
+    let later_nanos = later.seconds() as i128 * 1_000_000_000 + later.nanoseconds() as i128;
+    std::time::Duration::from_nanos((later_nanos - earlier_nanos).max(0) as u64)
+}
+
+/// Parse the zero-padded hex sequence number encoded at the start of an item's filename, e.g.
+/// `0000000000000001-xyz` or `0000000000000001-xyz.pop`.
+fn seq_prefix(path: &std::path::Path) -> Option<u64> {
+    let stem = path.file_stem()?.to_str()?;
+    let prefix = stem.split('-').next()?;
+    u64::from_str_radix(prefix, 16).ok()
+}
+
+/// Scan a spool directory for the highest sequence number among all entries, regardless of
+/// their stage (visible, `.inc`, or `.pop`).
+fn max_seq(path: &str) -> Result<Option<u64>, std::io::Error> {
+    let dirh = std::fs::read_dir(path)?;
+    let mut max = None;
+    for maybe_dirent in dirh {
+        let p = maybe_dirent?.path();
+        if let Some(seq) = seq_prefix(&p) {
+            max = Some(max.map_or(seq, |m: u64| m.max(seq)));
+        }
+    }
+    Ok(max)
+}
+
+/// List the visible (non-`.inc`, non-`.pop`) entries in a spool directory, ordered oldest-first
+/// by the sequence number encoded in their filename.
+fn visible_entries_sorted(path: &str) -> Result<Vec<std::path::PathBuf>, std::io::Error> {
+    let dirh = std::fs::read_dir(path)?;
+    let mut entries = Vec::new();
+    for maybe_dirent in dirh {
+        let p = maybe_dirent?.path();
+        if p.extension().is_some() {
+            continue;
+        }
+        if let Some(seq) = seq_prefix(&p) {
+            entries.push((seq, p));
+        }
+    }
+    entries.sort_by_key(|&(seq, _)| seq);
+    Ok(entries.into_iter().map(|(_, p)| p).collect())
+}
+
 #[cfg(test)]
 mod tests {
     use std;
@@ -260,6 +906,65 @@ mod tests {
         })
     }
 
+    #[test]
+    fn test_fifo_order() {
+        let (mut q, _cleanup) = new_queue();
+        for i in 0..20 {
+            assert!(q.push(Foo {
+                    i: i,
+                    b: i % 3 == 0,
+                    s: format!("#{}", i),
+                })
+                .is_ok());
+        }
+        for i in 0..20 {
+            let item = q.pop().unwrap().unwrap();
+            assert_eq!(item.i, i);
+        }
+        assert!(match q.pop() {
+            Ok(None) => true,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn test_seq_resumes_after_restart() {
+        let mut spool_path_buf = std::env::temp_dir();
+        spool_path_buf.push(rand_string());
+        let spool_dir = spool_path_buf.to_str().unwrap().to_string();
+        let _cleanup = cleanup::Cleanup::Dir(spool_dir.clone());
+
+        {
+            let mut q = Queue::<Foo>::new(&spool_dir).unwrap();
+            for i in 0..5 {
+                assert!(q.push(Foo {
+                        i: i,
+                        b: false,
+                        s: format!("#{}", i),
+                    })
+                    .is_ok());
+            }
+        }
+
+        // Re-opening the same spool directory must resume seq past the existing entries, so
+        // a newly pushed item sorts after everything already there.
+        let mut q = Queue::<Foo>::new(&spool_dir).unwrap();
+        assert!(q.push(Foo {
+                i: 5,
+                b: false,
+                s: "#5".to_string(),
+            })
+            .is_ok());
+        for i in 0..6 {
+            let item = q.pop().unwrap().unwrap();
+            assert_eq!(item.i, i);
+        }
+        assert!(match q.pop() {
+            Ok(None) => true,
+            _ => false,
+        });
+    }
+
     #[test]
     fn test_push_pop_many() {
         let (mut q, _cleanup) = new_queue();
@@ -336,6 +1041,181 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_encrypted_push_pop() {
+        let mut spool_path_buf = std::env::temp_dir();
+        spool_path_buf.push(rand_string());
+        let spool_dir = spool_path_buf.to_str().unwrap().to_string();
+        let _cleanup = cleanup::Cleanup::Dir(spool_dir.clone());
+
+        let key = [7u8; KEY_LEN];
+        let mut q = Queue::<Foo>::new_encrypted(&spool_dir, key).unwrap();
+        assert!(q.push(Foo {
+                i: 42,
+                b: true,
+                s: "super secret".to_string(),
+            })
+            .is_ok());
+
+        // The item file on disk must not contain the plaintext payload.
+        let item_entry = std::fs::read_dir(&spool_dir)
+            .unwrap()
+            .map(|e| e.unwrap())
+            .find(|e| e.path().extension().is_none())
+            .unwrap();
+        let on_disk = std::fs::read(item_entry.path()).unwrap();
+        let on_disk_str = String::from_utf8_lossy(&on_disk);
+        assert!(!on_disk_str.contains("super secret"));
+        assert!(!on_disk_str.contains("42"));
+
+        let result = q.pop().unwrap().unwrap();
+        assert_eq!(result,
+                   Foo {
+                       i: 42,
+                       b: true,
+                       s: "super secret".to_string(),
+                   });
+        assert!(match q.pop() {
+            Ok(None) => true,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn test_reclaim() {
+        let (mut q, _cleanup) = new_queue();
+        assert!(q.push(Foo {
+                i: 1,
+                b: false,
+                s: "reclaim-me".to_string(),
+            })
+            .is_ok());
+        let popped = q.pop().unwrap().unwrap();
+
+        // Still within the visibility timeout: reclaim must not redeliver it yet.
+        q.reclaim(std::time::Duration::from_millis(200)).unwrap();
+        assert!(match q.pop() {
+            Ok(None) => true,
+            _ => false,
+        });
+
+        // Once the timeout has elapsed, reclaim redelivers the item.
+        std::thread::sleep(std::time::Duration::from_millis(250));
+        q.reclaim(std::time::Duration::from_millis(200)).unwrap();
+        let redelivered = q.pop().unwrap().unwrap();
+        assert_eq!(redelivered, popped);
+    }
+
+    #[test]
+    fn test_stream_redelivers_expired_item_while_idle() {
+        let (mut q, _cleanup) = new_queue();
+        assert!(q.push(Foo {
+                i: 1,
+                b: false,
+                s: "reclaim-me".to_string(),
+            })
+            .is_ok());
+        let popped = q.pop().unwrap().unwrap();
+
+        // No further push will ever happen, so the fs watcher alone would never wake this
+        // stream; only the timer armed against the item's visibility timeout can redeliver it.
+        let qs = QueueStream::new_with_visibility_timeout(q, std::time::Duration::from_millis(200));
+        let redelivered = qs.take(1).collect().wait().unwrap();
+        assert_eq!(redelivered, vec![popped]);
+    }
+
+    #[test]
+    fn test_dump_restore() {
+        let (mut q, _cleanup) = new_queue();
+        for i in 0..10 {
+            assert!(q.push(Foo {
+                    i: i,
+                    b: i % 3 == 0,
+                    s: format!("#{}", i),
+                })
+                .is_ok());
+        }
+
+        let mut archive = Vec::new();
+        q.dump(&mut archive).unwrap();
+
+        let (mut restored, _restored_cleanup) = new_queue();
+        restored.restore(archive.as_slice()).unwrap();
+
+        for i in 0..10 {
+            let item = restored.pop().unwrap().unwrap();
+            assert_eq!(item,
+                       Foo {
+                           i: i,
+                           b: i % 3 == 0,
+                           s: format!("#{}", i),
+                       });
+        }
+        assert!(match restored.pop() {
+            Ok(None) => true,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn test_push_pop_batch() {
+        let (mut q, _cleanup) = new_queue();
+        let items: Vec<Foo> = (0..20)
+            .map(|i| {
+                Foo {
+                    i: i,
+                    b: i % 3 == 0,
+                    s: format!("#{}", i),
+                }
+            })
+            .collect();
+        q.push_batch(items).unwrap();
+
+        let first = q.pop_batch(12).unwrap();
+        assert_eq!(first.len(), 12);
+        let second = q.pop_batch(20).unwrap();
+        assert_eq!(second.len(), 8);
+        for (idx, item) in first.iter().chain(second.iter()).enumerate() {
+            assert_eq!(item.i, idx as i32);
+        }
+        assert!(q.pop_batch(1).unwrap().is_empty());
+    }
+
+    #[cfg(all(target_os = "linux", feature = "io_uring"))]
+    #[test]
+    fn test_io_uring_batch_round_trip_skips_malformed() {
+        let mut spool_path_buf = std::env::temp_dir();
+        spool_path_buf.push(rand_string());
+        let spool_dir = spool_path_buf.to_str().unwrap().to_string();
+        let _cleanup = cleanup::Cleanup::Dir(spool_dir.clone());
+
+        let mut q = Queue::<Foo>::new(&spool_dir).unwrap();
+        let items: Vec<Foo> = (0..10)
+            .map(|i| {
+                Foo {
+                    i: i,
+                    b: i % 2 == 0,
+                    s: format!("#{}", i),
+                }
+            })
+            .collect();
+        q.push_batch(items).unwrap();
+
+        // Stage a malformed entry directly (not valid JSON), to confirm pop_batch decodes
+        // before renaming and leaves it visible rather than losing it.
+        let mut bad_path = spool_path_buf.clone();
+        bad_path.push("00000000-bad");
+        std::fs::write(&bad_path, b"not json").unwrap();
+
+        let popped = q.pop_batch(20).unwrap();
+        assert_eq!(popped.len(), 10);
+        for (idx, item) in popped.iter().enumerate() {
+            assert_eq!(item.i, idx as i32);
+        }
+        assert!(bad_path.exists());
+        assert!(q.pop_batch(1).unwrap().is_empty());
+    }
+
     #[test]
     fn test_push_in_stream_out() {
         let (q, _cleanup) = new_queue();